@@ -4,36 +4,372 @@
 #[cfg(feature = "panic_halt")]
 use panic_halt as _;
 
-use cortex_m::interrupt::{free as interrupt_free, Mutex};
+use cortex_m::interrupt::{free as interrupt_free, CriticalSection, Mutex};
 use cortex_m_rt::entry;
 
-use microbit::hal::gpio::{Level, Pin};
+use microbit::hal::gpio::p0::P0_05;
+use microbit::hal::gpio::{Disconnected, Level, Pin};
 use microbit::hal::gpio::{Output, PushPull};
 
-use microbit::hal::prelude::OutputPin;
-use microbit::hal::{
-    pac::{interrupt, RTC0},
-    rtc::{Rtc, RtcInterrupt},
-};
+use microbit::hal::pac::{interrupt, PWM0, PWM1, PWM2, RTC0};
+use microbit::hal::pwm::{Channel as PwmChannel, Instance as PwmInstance, Pwm};
+use microbit::hal::rtc::{Rtc, RtcInterrupt};
+use microbit::hal::saadc::{Resolution, Saadc, SaadcConfig};
+use microbit::hal::time::Hertz;
+use microbit::hal::uarte::{Baudrate, Parity, Uarte};
+use microbit::pac::UARTE0;
+
+#[cfg(feature = "eeprom")]
+use microbit::hal::pac::TWIM0;
+#[cfg(feature = "eeprom")]
+use microbit::hal::twim::{Frequency as TwimFrequency, Pins as TwimPins, Twim};
 
 use microbit::board::Board;
 use microbit::hal::clocks::Clocks;
 
-struct Program<const N: usize> {
-    ctl: Pin<Output<PushPull>>,
-    schema: [i16; N],
+use core::fmt::Write as _;
+use heapless::{String, Vec};
+
+// Longest schema a `SET` command can install; comfortably above the longest
+// compiled-in mix (`GRE_MIX`, 13 steps).
+const MAX_SCHEMA_LEN: usize = 32;
+
+// Longest line the UART control port needs to parse: `NIGHT <ch> <hour> `
+// followed by up to `MAX_SCHEMA_LEN` `duty,millis` tokens (each up to 9
+// bytes plus a separating space), with slack for the command word and
+// numeric fields.
+const MAX_LINE_LEN: usize = 16 + MAX_SCHEMA_LEN * 10;
+
+// Longest `GET` reply: `CHn next=.. night=.. threshold=.. ` plus up to
+// `MAX_SCHEMA_LEN` ` duty,millis` tokens (each up to 10 bytes).
+const MAX_REPLY_LEN: usize = 48 + MAX_SCHEMA_LEN * 10;
+
+// Gates whether the scheduler advances schemas at all; toggled by the `RUN`
+// and `STOP` UART commands. Dithering keeps running either way so a stopped
+// channel holds its last brightness instead of going dark.
+static RUNNING: AtomicBool = AtomicBool::new(true);
+
+// Brightness runs 0..=255 regardless of which `Dither` backend drives a
+// channel, so a schema step's duty is a fraction (out of 255) of full
+// brightness instead of a bare on/off flag.
+const PWM_FREQ_HZ: u32 = 1_000;
+
+// Number of LED channels the scheduler drives; bump this, add a
+// `ChannelDriver` variant for its output and push another `Channel` in
+// `entry` to add a fourth color.
+const NUM_CHANNELS: usize = 3;
+
+// Speed knob: every `SAMPLE_PERIOD_TICKS` ms we take one SAADC reading and
+// turn it into a fixed-point multiplier (in thousandths, so 1000 == 1.0x)
+// applied to every channel's reload interval. 0.25x..2.0x keeps the fastest
+// setting still perceptible and the slowest still lively.
+const SAMPLE_PERIOD_TICKS: u16 = 256;
+const ADC_MAX: u32 = 4095;
+const SCALE_DEN: u32 = 1000;
+const SCALE_MIN_NUM: u32 = 250;
+const SCALE_RANGE_NUM: u32 = 1750;
+
+static SCALE_NUM: AtomicU16 = AtomicU16::new(SCALE_DEN as u16);
+
+#[derive(Clone, Copy)]
+struct Step {
+    duty: u8,
+    millis: u16,
+}
+
+/// A channel output that can be driven to a brightness level. Implementors
+/// that need per-tick work (e.g. software dithering) override `tick`; a
+/// free-running hardware PWM channel has nothing to do there.
+trait Dither {
+    fn set_brightness(&mut self, brightness: u8);
+
+    fn tick(&mut self) {}
+}
+
+impl<T: PwmInstance> Dither for Pwm<T> {
+    fn set_brightness(&mut self, brightness: u8) {
+        self.set_duty_on(PwmChannel::C0, brightness as u16);
+    }
+}
+
+/// Pulse-density brightness for boards/pins without a hardware PWM channel:
+/// every RTC tick nudges a sigma-delta accumulator by `brightness` and lights
+/// the pin for ticks where that addition carries, so the pin is lit roughly
+/// `brightness / 255` of the time with no flicker visible at 1 kHz.
+struct SoftPwm {
+    pin: Pin<Output<PushPull>>,
+    brightness: u8,
+    accumulator: u8,
+}
+
+impl Dither for SoftPwm {
+    fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+
+    fn tick(&mut self) {
+        match self.brightness {
+            0 => _ = self.pin.set_low(),
+            255 => _ = self.pin.set_high(),
+            brightness => {
+                let (sum, carry) = self.accumulator.overflowing_add(brightness);
+                self.accumulator = sum;
+                if carry {
+                    _ = self.pin.set_high();
+                } else {
+                    _ = self.pin.set_low();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "soft_pwm"))]
+type RedDriver = Pwm<PWM0>;
+#[cfg(not(feature = "soft_pwm"))]
+type YelDriver = Pwm<PWM1>;
+#[cfg(not(feature = "soft_pwm"))]
+type GreDriver = Pwm<PWM2>;
+
+#[cfg(feature = "soft_pwm")]
+type RedDriver = SoftPwm;
+#[cfg(feature = "soft_pwm")]
+type YelDriver = SoftPwm;
+#[cfg(feature = "soft_pwm")]
+type GreDriver = SoftPwm;
+
+#[cfg(not(feature = "soft_pwm"))]
+fn driver_for<T: PwmInstance>(pwm: T, pin: Pin<Output<PushPull>>) -> Pwm<T> {
+    let pwm = Pwm::new(pwm);
+    pwm.set_output_pin(PwmChannel::C0, pin);
+    pwm.set_period(Hertz(PWM_FREQ_HZ));
+    pwm.set_max_duty(u8::MAX as u16);
+    pwm.enable();
+    pwm
+}
+
+#[cfg(feature = "soft_pwm")]
+fn driver_for<T>(_pwm: T, pin: Pin<Output<PushPull>>) -> SoftPwm {
+    SoftPwm {
+        pin,
+        brightness: 0,
+        accumulator: 0,
+    }
+}
+
+/// The concrete output behind a scheduled channel. A plain enum (rather than
+/// `dyn Dither`) keeps the scheduler's `Vec` homogeneous without needing an
+/// allocator, at the cost of one match per call.
+enum ChannelDriver {
+    Red(RedDriver),
+    Yel(YelDriver),
+    Gre(GreDriver),
+}
+
+impl Dither for ChannelDriver {
+    fn set_brightness(&mut self, brightness: u8) {
+        match self {
+            ChannelDriver::Red(d) => d.set_brightness(brightness),
+            ChannelDriver::Yel(d) => d.set_brightness(brightness),
+            ChannelDriver::Gre(d) => d.set_brightness(brightness),
+        }
+    }
+
+    fn tick(&mut self) {
+        match self {
+            ChannelDriver::Red(d) => d.tick(),
+            ChannelDriver::Yel(d) => d.tick(),
+            ChannelDriver::Gre(d) => d.tick(),
+        }
+    }
+}
+
+struct Channel {
+    driver: ChannelDriver,
+    day_schema: Vec<Step, MAX_SCHEMA_LEN>,
     next: usize,
+    countdown: u16,
+    night_schema: Option<Vec<Step, MAX_SCHEMA_LEN>>,
+    night_threshold: Option<u8>,
+    is_night: bool,
+}
+
+impl Channel {
+    fn new(driver: ChannelDriver, schema: &[Step]) -> Self {
+        Channel {
+            driver,
+            day_schema: Vec::from_slice(schema).unwrap(),
+            next: 0,
+            countdown: 1,
+            night_schema: None,
+            night_threshold: None,
+            is_night: false,
+        }
+    }
+
+    /// The schema currently in effect — `night_schema` while `is_night` is
+    /// set (falling back to the day table if none was configured), else
+    /// `day_schema`. `day_schema`/`night_schema` are fixed, named slots;
+    /// only which one is *active* changes, never their storage.
+    fn active_schema(&self) -> &Vec<Step, MAX_SCHEMA_LEN> {
+        if self.is_night {
+            self.night_schema.as_ref().unwrap_or(&self.day_schema)
+        } else {
+            &self.day_schema
+        }
+    }
+
+    fn tick(&mut self, running: bool) {
+        self.driver.tick();
+
+        if !running {
+            return;
+        }
+
+        self.countdown -= 1;
+        if self.countdown == 0 {
+            let schema = if self.is_night {
+                self.night_schema.as_ref().unwrap_or(&self.day_schema)
+            } else {
+                &self.day_schema
+            };
+            let interval = adv_prg_nucleus(schema, &mut self.next, &mut self.driver);
+            let scale_num = SCALE_NUM.load(Ordering::Relaxed);
+            // Never let a tiny/zero interval stall the schema forever.
+            self.countdown = scale_interval(interval, scale_num).max(1);
+        }
+    }
+
+    /// Flip the active day/night schema appropriate for `hour`, if a night
+    /// schema has been configured for this channel.
+    fn update_for_hour(&mut self, hour: u8) {
+        let Some(threshold) = self.night_threshold else {
+            return;
+        };
+        let want_night = hour >= threshold;
+        if want_night == self.is_night {
+            return;
+        }
+        self.is_night = want_night;
+        // The newly active table may be shorter; don't index past its end.
+        if self.next >= self.active_schema().len() {
+            self.next = 0;
+        }
+    }
+}
+
+fn scale_interval(interval: u16, scale_num: u16) -> u16 {
+    ((interval as u32 * scale_num as u32) / SCALE_DEN).min(u16::MAX as u32) as u16
 }
 
 use core::cell::RefCell;
-use core::sync::atomic::{AtomicU16, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU16, Ordering};
 
-static RED_PRG: Mutex<RefCell<Option<Program<10>>>> = Mutex::new(RefCell::new(None));
-static YEL_PRG: Mutex<RefCell<Option<Program<8>>>> = Mutex::new(RefCell::new(None));
-static GRE_PRG: Mutex<RefCell<Option<Program<13>>>> = Mutex::new(RefCell::new(None));
+static SCHEDULER: Mutex<RefCell<Option<Vec<Channel, NUM_CHANNELS>>>> =
+    Mutex::new(RefCell::new(None));
 
 static RTC: Mutex<RefCell<Option<Rtc<RTC0>>>> = Mutex::new(RefCell::new(None));
 
+type ScalePin = P0_05<Disconnected>;
+static SAADC: Mutex<RefCell<Option<(Saadc, ScalePin)>>> = Mutex::new(RefCell::new(None));
+
+/// Wall-clock time of day, advanced one tick (~1 ms) at a time from RTC0.
+/// There's no battery-backed calendar on this board, so `hours`/`minutes`
+/// start at zero at boot and drift like any other free-running clock;
+/// `TIME` lets the user set it.
+struct Clock {
+    ticks: u16,
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+}
+
+impl Clock {
+    /// Advance by one RTC tick. Returns `true` if `hours` just changed, so
+    /// the caller knows it's worth re-checking day/night schema swaps.
+    fn tick(&mut self) -> bool {
+        self.ticks += 1;
+        if self.ticks < 1000 {
+            return false;
+        }
+        self.ticks = 0;
+
+        self.seconds += 1;
+        if self.seconds < 60 {
+            return false;
+        }
+        self.seconds = 0;
+
+        self.minutes += 1;
+        if self.minutes < 60 {
+            return false;
+        }
+        self.minutes = 0;
+
+        self.hours = (self.hours + 1) % 24;
+        true
+    }
+}
+
+static CLOCK: Mutex<RefCell<Clock>> = Mutex::new(RefCell::new(Clock {
+    ticks: 0,
+    seconds: 0,
+    minutes: 0,
+    hours: 0,
+}));
+
+fn apply_time_of_day(cs: &CriticalSection, hour: u8) {
+    if let Some(channels) = SCHEDULER.borrow(cs).borrow_mut().as_mut() {
+        for channel in channels.iter_mut() {
+            channel.update_for_hour(hour);
+        }
+    }
+}
+
+#[cfg(feature = "eeprom")]
+static TWIM: Mutex<RefCell<Option<Twim<TWIM0>>>> = Mutex::new(RefCell::new(None));
+
+static RED_MIX: [Step; 10] = [
+    Step { duty: u8::MAX, millis: 1000 },
+    Step { duty: 0, millis: 1200 },
+    Step { duty: u8::MAX, millis: 800 },
+    Step { duty: 0, millis: 300 },
+    Step { duty: u8::MAX, millis: 100 },
+    Step { duty: 0, millis: 100 },
+    Step { duty: u8::MAX, millis: 200 },
+    Step { duty: 0, millis: 200 },
+    Step { duty: u8::MAX, millis: 140 },
+    Step { duty: 0, millis: 134 },
+];
+
+static YEL_MIX: [Step; 8] = [
+    Step { duty: u8::MAX, millis: 800 },
+    Step { duty: 0, millis: 300 },
+    Step { duty: u8::MAX, millis: 330 },
+    Step { duty: 0, millis: 370 },
+    Step { duty: u8::MAX, millis: 550 },
+    Step { duty: 0, millis: 880 },
+    Step { duty: u8::MAX, millis: 123 },
+    Step { duty: 0, millis: 555 },
+];
+
+#[rustfmt::skip]
+static GRE_MIX: [Step; 13] = [
+    Step { duty: 0, millis: 890 },
+    Step { duty: u8::MAX, millis: 990 },
+    Step { duty: 0, millis: 1111 },
+    Step { duty: u8::MAX, millis: 876 },
+    Step { duty: 0, millis: 345 },
+    Step { duty: u8::MAX, millis: 875 },
+    Step { duty: 0, millis: 432 },
+    Step { duty: u8::MAX, millis: 777 },
+    Step { duty: 0, millis: 321 },
+    Step { duty: u8::MAX, millis: 444 },
+    Step { duty: 0, millis: 1000 },
+    Step { duty: u8::MAX, millis: 100 },
+    Step { duty: 0, millis: 100 },
+];
+
 #[entry]
 fn entry() -> ! {
     let brd = Board::take().unwrap();
@@ -52,96 +388,462 @@ fn entry() -> ! {
     rtc.enable_counter();
 
     let pins = brd.pins;
-    let red_ctl = pins.p0_02.into_push_pull_output(Level::Low).degrade();
-    let yel_ctl = pins.p0_03.into_push_pull_output(Level::Low).degrade();
-    let gre_ctl = pins.p0_04.into_push_pull_output(Level::Low).degrade();
-
-    let red_mix = [1000, -1200, 800, -300, 100, -100, 200, -200, 140, -134_i16];
-    let yel_mix = [800, -300, 330, -370, 550, -880, 123, -555_i16];
-    #[rustfmt::skip]
-    let gre_mix = [-890, 990, -1111, 876, -345, 875, -432, 777, -321, 444, -1000, 100, -100_i16,];
-
-    let red_prg = Program {
-        ctl: red_ctl,
-        schema: red_mix,
-        next: 0,
-    };
-
-    let yel_prg = Program {
-        ctl: yel_ctl,
-        schema: yel_mix,
-        next: 0,
+    let red_ctl: Pin<Output<PushPull>> = pins.p0_02.into_push_pull_output(Level::Low).degrade();
+    let yel_ctl: Pin<Output<PushPull>> = pins.p0_03.into_push_pull_output(Level::Low).degrade();
+    let gre_ctl: Pin<Output<PushPull>> = pins.p0_04.into_push_pull_output(Level::Low).degrade();
+
+    let red_drv = ChannelDriver::Red(driver_for(brd.PWM0, red_ctl));
+    let yel_drv = ChannelDriver::Yel(driver_for(brd.PWM1, yel_ctl));
+    let gre_drv = ChannelDriver::Gre(driver_for(brd.PWM2, gre_ctl));
+
+    #[cfg(feature = "eeprom")]
+    let mut twim = Twim::new(
+        brd.TWIM0,
+        TwimPins {
+            scl: pins.p0_08.into_floating_input().degrade(),
+            sda: pins.p0_16.into_floating_input().degrade(),
+        },
+        TwimFrequency::K100,
+    );
+    #[cfg(feature = "eeprom")]
+    let loaded = eeprom::load(&mut twim);
+    #[cfg(not(feature = "eeprom"))]
+    let loaded: Option<[Vec<Step, MAX_SCHEMA_LEN>; NUM_CHANNELS]> = None;
+
+    let red_schema: &[Step] = loaded.as_ref().map_or(&RED_MIX, |s| s[0].as_slice());
+    let yel_schema: &[Step] = loaded.as_ref().map_or(&YEL_MIX, |s| s[1].as_slice());
+    let gre_schema: &[Step] = loaded.as_ref().map_or(&GRE_MIX, |s| s[2].as_slice());
+
+    let mut channels = Vec::new();
+    _ = channels.push(Channel::new(red_drv, red_schema));
+    _ = channels.push(Channel::new(yel_drv, yel_schema));
+    _ = channels.push(Channel::new(gre_drv, gre_schema));
+
+    // ADC_MAX below assumes 12-bit samples; pin the resolution explicitly
+    // rather than relying on whatever the HAL defaults to.
+    let saadc_config = SaadcConfig {
+        resolution: Resolution::_12BIT,
+        ..SaadcConfig::default()
     };
+    let saadc = Saadc::new(brd.SAADC, saadc_config);
+    let scale_pin = pins.p0_05;
 
-    let gre_prg = Program {
-        ctl: gre_ctl,
-        schema: gre_mix,
-        next: 0,
-    };
+    let mut uarte = Uarte::new(
+        brd.UARTE0,
+        brd.uart.into_uarte_pins(),
+        Parity::EXCLUDED,
+        Baudrate::BAUD115200,
+    );
 
     interrupt_free(move |cs| {
-        _ = RED_PRG.borrow(cs).borrow_mut().replace(red_prg);
-        _ = YEL_PRG.borrow(cs).borrow_mut().replace(yel_prg);
-        _ = GRE_PRG.borrow(cs).borrow_mut().replace(gre_prg);
+        _ = SCHEDULER.borrow(cs).borrow_mut().replace(channels);
+        _ = SAADC.borrow(cs).borrow_mut().replace((saadc, scale_pin));
         _ = RTC.borrow(cs).borrow_mut().replace(rtc);
+        #[cfg(feature = "eeprom")]
+        _ = TWIM.borrow(cs).borrow_mut().replace(twim);
     });
 
-    loop {}
+    let mut line: Vec<u8, MAX_LINE_LEN> = Vec::new();
+    let mut discarding = false;
+    loop {
+        sample_scale_if_pending();
+
+        let mut byte = [0u8; 1];
+        if uarte.read(&mut byte).is_err() {
+            continue;
+        }
+
+        match byte[0] {
+            b'\r' | b'\n' => {
+                if discarding {
+                    discarding = false;
+                    line.clear();
+                } else if !line.is_empty() {
+                    if let Ok(text) = core::str::from_utf8(&line) {
+                        process_command(text, &mut uarte);
+                    }
+                    line.clear();
+                }
+            }
+            b if discarding => {
+                // Already resyncing for an overflowed line; wait for the
+                // terminator above instead of re-triggering the overflow path.
+                _ = b;
+            }
+            b => {
+                if line.push(b).is_err() {
+                    // Line too long for the buffer. The host is already
+                    // waiting on a reply for what it's sent so far, so
+                    // answer now instead of silently swallowing it, then
+                    // discard the rest of the line up to the terminator.
+                    _ = uarte.write(b"ERR\r\n");
+                    line.clear();
+                    discarding = true;
+                }
+            }
+        }
+    }
 }
 
+// Set by `RTC0` when a speed-knob sample is due, cleared by
+// `sample_scale_if_pending` once it has actually taken one. The ISR only
+// ever touches this flag — the (slow, blocking) SAADC conversion itself
+// always runs with interrupts enabled, outside any critical section.
+static SAMPLE_PENDING: AtomicBool = AtomicBool::new(false);
+
 #[interrupt]
 unsafe fn RTC0() {
-    static RED_COUNTDOWN: AtomicU16 = AtomicU16::new(1);
-    static YEL_COUNTDOWN: AtomicU16 = AtomicU16::new(1);
-    static GRE_COUNTDOWN: AtomicU16 = AtomicU16::new(1);
+    static ADC_COUNTDOWN: AtomicU16 = AtomicU16::new(SAMPLE_PERIOD_TICKS);
 
     interrupt_free(|cs| {
-        let mut borrow = RTC.borrow(cs).borrow_mut();
-        let rtc = borrow.take().unwrap();
+        let mut rtc_borrow = RTC.borrow(cs).borrow_mut();
+        let rtc = rtc_borrow.take().unwrap();
         rtc.reset_event(RtcInterrupt::Tick);
-        borrow.replace(rtc);
+        rtc_borrow.replace(rtc);
+
+        if ADC_COUNTDOWN.fetch_sub(1, Ordering::Relaxed) == 1 {
+            ADC_COUNTDOWN.store(SAMPLE_PERIOD_TICKS, Ordering::Relaxed);
+            SAMPLE_PENDING.store(true, Ordering::Relaxed);
+        }
+
+        let hour_changed = CLOCK.borrow(cs).borrow_mut().tick();
+        if hour_changed {
+            let hour = CLOCK.borrow(cs).borrow().hours;
+            apply_time_of_day(cs, hour);
+        }
+
+        let running = RUNNING.load(Ordering::Relaxed);
+        if let Some(channels) = SCHEDULER.borrow(cs).borrow_mut().as_mut() {
+            for channel in channels.iter_mut() {
+                channel.tick(running);
+            }
+        }
     });
+}
+
+/// Takes a speed-knob SAADC reading if `RTC0` flagged one as due. Must be
+/// called from regular (non-interrupt) context: the conversion itself runs
+/// with interrupts enabled, so `RTC0` keeps ticking the schedule on time
+/// instead of blocking on the ADC.
+fn sample_scale_if_pending() {
+    if !SAMPLE_PENDING.swap(false, Ordering::Relaxed) {
+        return;
+    }
+
+    let taken = interrupt_free(|cs| SAADC.borrow(cs).borrow_mut().take());
+    let Some((mut saadc, mut pin)) = taken else {
+        return;
+    };
+
+    if let Ok(raw) = saadc.read(&mut pin) {
+        let raw = raw.max(0) as u32;
+        let scale_num = SCALE_MIN_NUM + (SCALE_RANGE_NUM * raw) / ADC_MAX;
+        SCALE_NUM.store(scale_num as u16, Ordering::Relaxed);
+    }
+
+    interrupt_free(|cs| {
+        _ = SAADC.borrow(cs).borrow_mut().replace((saadc, pin));
+    });
+}
+
+/// Line protocol for the UART control port:
+///   SET <channel> <duty,millis> [<duty,millis> ...]   replace the day schema
+///   NIGHT <channel> <hour> <duty,millis> [...]        set the night schema + swap-over hour
+///   GET <channel>                                     dump schema + next + day/night state
+///   TIME <hh> <mm> <ss>                                set the wall clock
+///   RUN | STOP                                        gate schema advance
+///   SAVE                                               persist day schemas to EEPROM (feature = "eeprom")
+fn process_command(line: &str, uarte: &mut Uarte<UARTE0>) {
+    let mut parts = line.split_whitespace();
+    let reply_ok = match parts.next() {
+        Some("RUN") => {
+            RUNNING.store(true, Ordering::Relaxed);
+            true
+        }
+        Some("STOP") => {
+            RUNNING.store(false, Ordering::Relaxed);
+            true
+        }
+        Some("SET") => set_schema(parts),
+        Some("NIGHT") => set_night(parts),
+        Some("TIME") => set_time(parts),
+        Some("GET") => {
+            get_schema(parts, uarte);
+            return;
+        }
+        #[cfg(feature = "eeprom")]
+        Some("SAVE") => eeprom::save_current(),
+        _ => false,
+    };
+
+    _ = uarte.write(if reply_ok { b"OK\r\n" } else { b"ERR\r\n" });
+}
 
-    if RED_COUNTDOWN.fetch_sub(1, Ordering::Relaxed) == 1 {
-        adv_prg(&RED_COUNTDOWN, &RED_PRG);
+fn parse_steps<'a>(parts: impl Iterator<Item = &'a str>) -> Option<Vec<Step, MAX_SCHEMA_LEN>> {
+    let mut schema: Vec<Step, MAX_SCHEMA_LEN> = Vec::new();
+    for token in parts {
+        let (duty, millis) = token.split_once(',')?;
+        let (duty, millis) = (duty.parse::<u8>().ok()?, millis.parse::<u16>().ok()?);
+        schema.push(Step { duty, millis }).ok()?;
+    }
+    if schema.is_empty() {
+        return None;
     }
+    Some(schema)
+}
 
-    if YEL_COUNTDOWN.fetch_sub(1, Ordering::Relaxed) == 1 {
-        adv_prg(&YEL_COUNTDOWN, &YEL_PRG);
+fn set_schema<'a>(mut parts: impl Iterator<Item = &'a str>) -> bool {
+    let Some(channel) = parts.next().and_then(|s| s.parse::<usize>().ok()) else {
+        return false;
+    };
+    let Some(schema) = parse_steps(parts) else {
+        return false;
+    };
+
+    interrupt_free(|cs| {
+        let mut borrow = SCHEDULER.borrow(cs).borrow_mut();
+        match borrow.as_mut().and_then(|channels| channels.get_mut(channel)) {
+            Some(ch) => {
+                ch.day_schema = schema;
+                if !ch.is_night {
+                    ch.next = 0;
+                    ch.countdown = 1;
+                }
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+fn set_night<'a>(mut parts: impl Iterator<Item = &'a str>) -> bool {
+    let Some(channel) = parts.next().and_then(|s| s.parse::<usize>().ok()) else {
+        return false;
+    };
+    let Some(threshold) = parts.next().and_then(|s| s.parse::<u8>().ok()) else {
+        return false;
+    };
+    if threshold >= 24 {
+        return false;
     }
+    let Some(schema) = parse_steps(parts) else {
+        return false;
+    };
+
+    interrupt_free(|cs| {
+        let hour = CLOCK.borrow(cs).borrow().hours;
+        let mut borrow = SCHEDULER.borrow(cs).borrow_mut();
+        match borrow.as_mut().and_then(|channels| channels.get_mut(channel)) {
+            Some(ch) => {
+                ch.night_threshold = Some(threshold);
+                ch.night_schema = Some(schema);
+                ch.is_night = false;
+                ch.update_for_hour(hour);
+                true
+            }
+            None => false,
+        }
+    })
+}
 
-    if GRE_COUNTDOWN.fetch_sub(1, Ordering::Relaxed) == 1 {
-        adv_prg(&GRE_COUNTDOWN, &GRE_PRG);
+fn set_time<'a>(mut parts: impl Iterator<Item = &'a str>) -> bool {
+    let Some(hours) = parts.next().and_then(|s| s.parse::<u8>().ok()) else {
+        return false;
+    };
+    let Some(minutes) = parts.next().and_then(|s| s.parse::<u8>().ok()) else {
+        return false;
+    };
+    let Some(seconds) = parts.next().and_then(|s| s.parse::<u8>().ok()) else {
+        return false;
+    };
+    if hours >= 24 || minutes >= 60 || seconds >= 60 {
+        return false;
     }
+
+    interrupt_free(|cs| {
+        {
+            let mut clock = CLOCK.borrow(cs).borrow_mut();
+            clock.ticks = 0;
+            clock.seconds = seconds;
+            clock.minutes = minutes;
+            clock.hours = hours;
+        }
+        apply_time_of_day(cs, hours);
+    });
+    true
 }
 
-fn adv_prg<const N: usize>(countdown: &AtomicU16, prg: &Mutex<RefCell<Option<Program<N>>>>) {
-    let interval = interrupt_free(|cs| {
-        let mut borrow = prg.borrow(cs).borrow_mut();
-        let mut prg = borrow.take().unwrap();
+fn get_schema<'a>(mut parts: impl Iterator<Item = &'a str>, uarte: &mut Uarte<UARTE0>) {
+    let Some(channel) = parts.next().and_then(|s| s.parse::<usize>().ok()) else {
+        _ = uarte.write(b"ERR\r\n");
+        return;
+    };
 
-        let interval = adv_prg_nucleus(&prg.schema, &mut prg.next, &mut prg.ctl);
-        borrow.replace(prg);
-        interval
+    let mut line: String<MAX_REPLY_LEN> = String::new();
+    let found = interrupt_free(|cs| {
+        let borrow = SCHEDULER.borrow(cs).borrow_mut();
+        match borrow.as_ref().and_then(|channels| channels.get(channel)) {
+            Some(ch) => {
+                let mut ok = write!(line, "CH{} next={} night={}", channel, ch.next, ch.is_night)
+                    .is_ok();
+                if let Some(threshold) = ch.night_threshold {
+                    ok &= write!(line, " threshold={}", threshold).is_ok();
+                }
+                for step in ch.active_schema().iter() {
+                    ok &= write!(line, " {},{}", step.duty, step.millis).is_ok();
+                }
+                // A truncated reply is worse than none: the host would see
+                // a syntactically valid but incomplete schema dump with no
+                // indication bytes are missing, so report it as an error.
+                ok
+            }
+            None => false,
+        }
     });
 
-    countdown.swap(interval, Ordering::Relaxed);
+    if found {
+        _ = uarte.write(line.as_bytes());
+        _ = uarte.write(b"\r\n");
+    } else {
+        _ = uarte.write(b"ERR\r\n");
+    }
 }
 
-fn adv_prg_nucleus(schema: &[i16], next: &mut usize, ctl: &mut Pin<Output<PushPull>>) -> u16 {
+fn adv_prg_nucleus<D: Dither>(schema: &[Step], next: &mut usize, driver: &mut D) -> u16 {
     let next_val = *next;
-    let mut interval = schema[next_val];
+    let step = schema[next_val];
 
     *next = (next_val + 1) % schema.len();
 
-    if interval < 0 {
-        _ = ctl.set_low();
-        interval *= -1;
-    } else {
-        _ = ctl.set_high();
+    driver.set_brightness(step.duty);
+
+    step.millis
+}
+
+/// Nonvolatile schema storage on a 24C-series I2C EEPROM. Schemas are rarely
+/// anywhere near `MAX_SCHEMA_LEN` steps long, so the blob is variable-length:
+/// EEPROM address 0 holds a `u16` byte count for the blob that follows at
+/// address 2 — `MAGIC, channel count, (len, (duty, millis_hi, millis_lo) *
+/// len) * NUM_CHANNELS, crc16`. Storing the real length means `load` only
+/// has to read back exactly what `save_current` wrote, rather than a fixed
+/// `MAX_BLOB_LEN` window padded with whatever stale bytes happen to follow.
+/// A corrupt or partial write is rejected on the next boot via the CRC.
+#[cfg(feature = "eeprom")]
+mod eeprom {
+    use super::{
+        interrupt_free, Channel, Step, Twim, Vec, MAX_SCHEMA_LEN, NUM_CHANNELS, SCHEDULER, TWIM,
+        TWIM0,
+    };
+
+    const I2C_ADDR: u8 = 0x50;
+    const MAGIC: u8 = 0xD1;
+    const MAX_BLOB_LEN: usize = 2 + NUM_CHANNELS * (1 + MAX_SCHEMA_LEN * 3) + 2;
+    const LEN_HEADER_ADDR: u16 = 0;
+    const BLOB_ADDR: u16 = 2;
+    const FRAME_LEN: usize = 2 + 2 + MAX_BLOB_LEN;
+
+    fn crc16(data: &[u8]) -> u16 {
+        let mut crc: u16 = 0xFFFF;
+        for &byte in data {
+            crc ^= (byte as u16) << 8;
+            for _ in 0..8 {
+                crc = if crc & 0x8000 != 0 {
+                    (crc << 1) ^ 0x1021
+                } else {
+                    crc << 1
+                };
+            }
+        }
+        crc
     }
 
-    interval as u16
+    fn serialize(schemas: &[Vec<Step, MAX_SCHEMA_LEN>; NUM_CHANNELS]) -> Vec<u8, MAX_BLOB_LEN> {
+        let mut buf: Vec<u8, MAX_BLOB_LEN> = Vec::new();
+        _ = buf.push(MAGIC);
+        _ = buf.push(NUM_CHANNELS as u8);
+        for schema in schemas {
+            _ = buf.push(schema.len() as u8);
+            for step in schema.iter() {
+                _ = buf.push(step.duty);
+                let [hi, lo] = step.millis.to_be_bytes();
+                _ = buf.push(hi);
+                _ = buf.push(lo);
+            }
+        }
+        let [hi, lo] = crc16(&buf).to_be_bytes();
+        _ = buf.push(hi);
+        _ = buf.push(lo);
+        buf
+    }
+
+    fn deserialize(buf: &[u8]) -> Option<[Vec<Step, MAX_SCHEMA_LEN>; NUM_CHANNELS]> {
+        if buf.len() < 4 || buf[0] != MAGIC || buf[1] as usize != NUM_CHANNELS {
+            return None;
+        }
+
+        let (body, crc_bytes) = buf.split_at(buf.len() - 2);
+        if crc16(body) != u16::from_be_bytes([crc_bytes[0], crc_bytes[1]]) {
+            return None;
+        }
+
+        let mut schemas: [Vec<Step, MAX_SCHEMA_LEN>; NUM_CHANNELS] =
+            core::array::from_fn(|_| Vec::new());
+        let mut pos = 2;
+        for schema in schemas.iter_mut() {
+            let len = *body.get(pos)? as usize;
+            pos += 1;
+            if len == 0 || len > MAX_SCHEMA_LEN {
+                return None;
+            }
+            for _ in 0..len {
+                let duty = *body.get(pos)?;
+                let millis = u16::from_be_bytes([*body.get(pos + 1)?, *body.get(pos + 2)?]);
+                pos += 3;
+                schema.push(Step { duty, millis }).ok()?;
+            }
+        }
+        Some(schemas)
+    }
+
+    pub fn load(twim: &mut Twim<TWIM0>) -> Option<[Vec<Step, MAX_SCHEMA_LEN>; NUM_CHANNELS]> {
+        let mut len_buf = [0u8; 2];
+        twim.write_then_read(I2C_ADDR, &LEN_HEADER_ADDR.to_be_bytes(), &mut len_buf)
+            .ok()?;
+        let blob_len = u16::from_be_bytes(len_buf) as usize;
+        if blob_len == 0 || blob_len > MAX_BLOB_LEN {
+            return None;
+        }
+
+        let mut buf = [0u8; MAX_BLOB_LEN];
+        twim.write_then_read(I2C_ADDR, &BLOB_ADDR.to_be_bytes(), &mut buf[..blob_len])
+            .ok()?;
+        deserialize(&buf[..blob_len])
+    }
+
+    /// Snapshots the live schedule's day schemas and writes them to the
+    /// EEPROM, guarded by the same critical section `SET` uses so a
+    /// concurrent tick never observes a half-read `Channel`. Night schemas
+    /// and night thresholds are not saved.
+    pub fn save_current() -> bool {
+        let snapshot: [Vec<Step, MAX_SCHEMA_LEN>; NUM_CHANNELS] = interrupt_free(|cs| {
+            let borrow = SCHEDULER.borrow(cs).borrow_mut();
+            let channels: &Vec<Channel, NUM_CHANNELS> = borrow.as_ref().unwrap();
+            core::array::from_fn(|i| channels[i].day_schema.clone())
+        });
+
+        let blob = serialize(&snapshot);
+        let blob_len = blob.len() as u16;
+
+        let mut frame: Vec<u8, FRAME_LEN> = Vec::new();
+        _ = frame.extend_from_slice(&LEN_HEADER_ADDR.to_be_bytes());
+        _ = frame.extend_from_slice(&blob_len.to_be_bytes());
+        _ = frame.extend_from_slice(&blob);
+
+        interrupt_free(|cs| match TWIM.borrow(cs).borrow_mut().as_mut() {
+            Some(twim) => twim.write(I2C_ADDR, &frame).is_ok(),
+            None => false,
+        })
+    }
 }
 
 #[cfg(feature = "panic_abort")]
@@ -158,3 +860,5 @@ mod panic_abort {
 // cargo flash --target thumbv7em-none-eabihf --chip nRF52833_xxAA --features panic_halt
 // cargo build --release  --target thumbv7em-none-eabihf --features panic_abort
 // cargo build --target thumbv7em-none-eabihf --features panic_halt
+// cargo build --target thumbv7em-none-eabihf --features "panic_halt soft_pwm"
+// cargo build --target thumbv7em-none-eabihf --features "panic_halt eeprom"